@@ -34,5 +34,17 @@ pub use wasmer_compiler_cranelift::CraneliftConfig;
 #[cfg(feature = "compiler-llvm")]
 pub use wasmer_compiler_cranelift::LLVMConfig;
 
+#[cfg(feature = "compiler-llvm")]
+pub use wasmer_compiler_cranelift::coverage::{
+    CounterId, CounterKind, CoverageMap, Expression, ExprOp, FunctionCoverage, MappingEntry, Region,
+};
+
+// `Module::deserialize` (in `crate::module`) is the intended consumer of
+// these: given a `LoadedObject`, it maps `object_bytes` in and resolves
+// `header.trampoline_symbols` / `header.function_symbols` against it
+// instead of recompiling from wasm.
+#[cfg(feature = "compiler-llvm")]
+pub use wasmer_compiler_cranelift::aot::{compile_to_object, load_object, AotMetadata, LoadedObject};
+
 /// Version number of this crate.
 pub const VERSION: &str = env!("CARGO_PKG_VERSION");
@@ -0,0 +1,203 @@
+//! Platform ABI classification for the LLVM backend, modeled on
+//! `rustc_codegen_llvm`'s own `abi.rs`: given a target and a set of
+//! WebAssembly return types, decide how the native callee actually returns
+//! them, so the trampoline can be built to match instead of guessing from
+//! bitwidth patterns.
+
+use crate::intrinsics::Intrinsics;
+use inkwell::builder::Builder;
+use inkwell::context::Context;
+use inkwell::types::{BasicType, BasicTypeEnum, StructType};
+use inkwell::values::{BasicValueEnum, CallSiteValue, PointerValue};
+use inkwell::AddressSpace;
+use target_lexicon::Architecture;
+use wasmer_compiler::Target;
+use wasmer_runtime_core::types::{FuncSig, Type};
+
+use crate::trampolines::type_to_llvm;
+
+/// How a function's return values are actually passed back to the caller,
+/// per the target's C ABI.
+pub enum ReturnAbi<'ctx> {
+    /// No return values.
+    None,
+    /// A single scalar, returned directly.
+    Direct,
+    /// Several scalars that still fit in the platform's return registers
+    /// once coerced to one wide (or pair of) integer(s) -- e.g. two `i32`s
+    /// packed into an `i64` under x86_64 SysV.
+    Coerced(BasicTypeEnum<'ctx>),
+    /// The aggregate doesn't fit in registers and must be returned
+    /// indirectly: the caller allocates `ty`, passes a pointer to it as a
+    /// hidden first argument, and marks that argument `sret`.
+    Indirect(StructType<'ctx>),
+}
+
+/// Classifies `returns` for `target`, following the x86_64 SysV and AArch64
+/// AAPCS64 aggregate-return rules (two eightbytes or fewer come back in
+/// registers; anything larger, or containing a vector lane, is indirect).
+pub fn classify_return<'ctx>(
+    context: &'ctx Context,
+    intrinsics: &Intrinsics<'ctx>,
+    target: &Target,
+    returns: &[Type],
+) -> ReturnAbi<'ctx> {
+    if returns.is_empty() {
+        return ReturnAbi::None;
+    }
+    if returns.len() == 1 {
+        return ReturnAbi::Direct;
+    }
+
+    let bitwidths: Vec<u32> = returns
+        .iter()
+        .map(|ty| match ty {
+            Type::I32 | Type::F32 => 32,
+            Type::I64 | Type::F64 => 64,
+            Type::V128 => 128,
+        })
+        .collect();
+    let total_bits: u32 = bitwidths.iter().sum();
+    let has_vector = bitwidths.contains(&128);
+
+    let fits_in_registers = match target.triple().architecture {
+        // SysV x86_64: an aggregate of two eightbytes or fewer (<=128 bits,
+        // no vector lane) is classified INTEGER and returned in rax:rdx.
+        Architecture::X86_64 => total_bits <= 128 && !has_vector,
+        // AAPCS64: a composite of two eightbytes or fewer is returned in
+        // x0:x1; anything larger, homogeneous floating-point aggregates
+        // included, is classified indirect here.
+        Architecture::Arm(_) => total_bits <= 128 && !has_vector,
+        _ => false,
+    };
+
+    if !fits_in_registers {
+        let basic_types: Vec<_> = returns.iter().map(|&ty| type_to_llvm(intrinsics, ty)).collect();
+        return ReturnAbi::Indirect(context.struct_type(&basic_types, false));
+    }
+
+    let coerced_ty = if total_bits <= 64 {
+        intrinsics.i64_ty.as_basic_type_enum()
+    } else {
+        context
+            .struct_type(
+                &[
+                    intrinsics.i64_ty.as_basic_type_enum(),
+                    intrinsics.i64_ty.as_basic_type_enum(),
+                ],
+                false,
+            )
+            .as_basic_type_enum()
+    };
+    ReturnAbi::Coerced(coerced_ty)
+}
+
+/// Reads the return values out of a completed call site classified as
+/// `ReturnAbi::None` or `ReturnAbi::Direct` -- i.e. zero or one wasm return
+/// value, passed back as-is with no coercion to unpack.
+pub fn rets_from_call<'ctx>(
+    builder: &Builder<'ctx>,
+    intrinsics: &Intrinsics<'ctx>,
+    call_site: CallSiteValue<'ctx>,
+    func_sig: &FuncSig,
+) -> Vec<BasicValueEnum<'ctx>> {
+    match func_sig.returns() {
+        [] => vec![],
+        [_] => vec![call_site.try_as_basic_value().left().unwrap()],
+        _ => {
+            // A caller that hasn't classified its return through
+            // `classify_return` (the variadic path doesn't yet) can still
+            // land here with 2+ values; fall back to the same byte-level
+            // unpacking `rets_from_coerced` uses instead of assuming a
+            // particular packed-struct shape.
+            rets_from_coerced(builder, intrinsics, call_site, func_sig)
+        }
+    }
+}
+
+/// Reads the return values out of a completed call site classified as
+/// `ReturnAbi::Coerced(coerced_ty)`: the callee packed every wasm return
+/// value into `coerced_ty` (a bare wide integer, or a small struct of
+/// them) to fit the platform's return registers. The wasm-typed values
+/// have to be recovered at their original byte offsets -- `coerced_ty`'s
+/// own field count generally doesn't match `func_sig.returns().len()`, so
+/// extracting one struct field per wasm return (as the old bitwidth
+/// heuristic did) reads the wrong bits or panics outright.
+///
+/// This spills the coerced value to a stack slot and reloads each wasm
+/// return at its little-endian byte offset, which works regardless of how
+/// many integer lanes the coercion happened to use.
+pub fn rets_from_coerced<'ctx>(
+    builder: &Builder<'ctx>,
+    intrinsics: &Intrinsics<'ctx>,
+    call_site: CallSiteValue<'ctx>,
+    func_sig: &FuncSig,
+) -> Vec<BasicValueEnum<'ctx>> {
+    let coerced = call_site.try_as_basic_value().left().unwrap();
+    let slot = builder.build_alloca(coerced.get_type(), "coerced_ret");
+    builder.build_store(slot, coerced);
+    let base_ptr = builder.build_pointer_cast(slot, intrinsics.i8_ptr_ty, "");
+
+    // Mirror SysV's eightbyte packing: consecutive small values share an
+    // eightbyte when they fit, but a value never straddles an eightbyte
+    // boundary, so a 4-byte return right before an 8-byte one leaves its
+    // eightbyte's other 4 bytes padding rather than being packed tight.
+    let mut eightbyte_offset = 0u64;
+    let mut offset_in_eightbyte = 0u64;
+    func_sig
+        .returns()
+        .iter()
+        .map(|&ty| {
+            let size = match ty {
+                Type::I32 | Type::F32 => 4,
+                Type::I64 | Type::F64 => 8,
+                Type::V128 => 16,
+            };
+            if offset_in_eightbyte + size > 8 {
+                eightbyte_offset += 8;
+                offset_in_eightbyte = 0;
+            }
+            let byte_offset = eightbyte_offset + offset_in_eightbyte;
+
+            let field_ptr = unsafe {
+                builder.build_in_bounds_gep(
+                    base_ptr,
+                    &[intrinsics.i64_ty.const_int(byte_offset, false)],
+                    "ret_field",
+                )
+            };
+            let typed_ptr = builder.build_pointer_cast(
+                field_ptr,
+                type_to_llvm(intrinsics, ty).ptr_type(AddressSpace::Generic),
+                "",
+            );
+            let value = builder.build_load(typed_ptr, "ret");
+            offset_in_eightbyte += size;
+            value
+        })
+        .collect()
+}
+
+/// Reads the return values back out of an `sret` alloca after the call
+/// that wrote into it has returned.
+pub fn rets_from_sret<'ctx>(
+    builder: &Builder<'ctx>,
+    intrinsics: &Intrinsics<'ctx>,
+    sret_ptr: PointerValue<'ctx>,
+    func_sig: &FuncSig,
+) -> Vec<BasicValueEnum<'ctx>> {
+    func_sig
+        .returns()
+        .iter()
+        .enumerate()
+        .map(|(i, &ty)| {
+            let field_ptr = builder.build_struct_gep(sret_ptr, i as u32, "sret_field").unwrap();
+            let typed_ptr = builder.build_pointer_cast(
+                field_ptr,
+                type_to_llvm(intrinsics, ty).ptr_type(AddressSpace::Generic),
+                "",
+            );
+            builder.build_load(typed_ptr, "ret")
+        })
+        .collect()
+}
@@ -0,0 +1,217 @@
+//! Ahead-of-time (AOT) compilation: persist LLVM-compiled modules as
+//! relocatable native objects so later runs can skip the LLVM pipeline
+//! entirely, mirroring wasmtime's split of compile-time from runtime.
+//!
+//! [`compile_to_object`] asks the already-configured [`TargetMachine`]
+//! (see [`LLVMConfig::target_machine`](crate::config::LLVMConfig::target_machine))
+//! to emit a relocatable object -- ELF, Mach-O, or COFF, whichever the
+//! target calls for -- and writes it to disk next to a small metadata
+//! header. [`load_object`] reads that artifact back without touching LLVM
+//! at all; the caller only needs to `mmap` the object bytes and resolve
+//! the symbols the header names.
+
+use inkwell::module::Module;
+use inkwell::targets::{FileType, TargetMachine};
+use serde::{Deserialize, Serialize};
+use std::convert::TryInto;
+use std::io::{self, Write};
+use std::path::Path;
+
+const MAGIC: &[u8; 8] = b"WASMAOT\0";
+const HEADER_VERSION: u32 = 1;
+
+/// Everything needed to reload a cached native object without re-running
+/// LLVM: the symbol names it exports and the layout `wasmer_runtime_core`
+/// needs to wire the module back up.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AotMetadata {
+    /// Format version of this header; bumped whenever the layout changes.
+    pub version: u32,
+    /// Trampoline symbol names, e.g. `trmp0`, `trmp1`, ..., indexed by
+    /// `SigIndex`.
+    pub trampoline_symbols: Vec<String>,
+    /// Per-function compiled symbol names, indexed by local function index.
+    pub function_symbols: Vec<String>,
+    /// Initial linear memory size, in 64 KiB pages, if the module defines one.
+    pub memory_pages: Option<u32>,
+    /// Number of table elements reserved at instantiation, if the module
+    /// defines a table.
+    pub table_elements: Option<u32>,
+}
+
+impl AotMetadata {
+    pub fn new(
+        trampoline_symbols: Vec<String>,
+        function_symbols: Vec<String>,
+        memory_pages: Option<u32>,
+        table_elements: Option<u32>,
+    ) -> Self {
+        Self {
+            version: HEADER_VERSION,
+            trampoline_symbols,
+            function_symbols,
+            memory_pages,
+            table_elements,
+        }
+    }
+}
+
+/// Compiles `module` down to a relocatable native object and writes it to
+/// `path`, preceded by `header` so [`load_object`] can make sense of it.
+pub fn compile_to_object(
+    target_machine: &TargetMachine,
+    module: &Module,
+    header: &AotMetadata,
+    path: &Path,
+) -> io::Result<()> {
+    let object_buffer = target_machine
+        .write_to_memory_buffer(module, FileType::Object)
+        .map_err(|err| io::Error::new(io::ErrorKind::Other, err.to_string()))?;
+
+    let encoded_header = bincode::serialize(header)
+        .map_err(|err| io::Error::new(io::ErrorKind::Other, err.to_string()))?;
+
+    let mut file = std::fs::File::create(path)?;
+    file.write_all(MAGIC)?;
+    file.write_all(&(encoded_header.len() as u64).to_le_bytes())?;
+    file.write_all(&encoded_header)?;
+    file.write_all(object_buffer.as_slice())?;
+    Ok(())
+}
+
+/// A cached artifact read back from disk: the metadata plus the raw object
+/// bytes, ready to be mapped in by the `wasmer` crate's loader.
+pub struct LoadedObject {
+    pub header: AotMetadata,
+    pub object_bytes: Vec<u8>,
+}
+
+/// Reads an artifact written by [`compile_to_object`] without invoking
+/// LLVM. The caller (`wasmer::Module::deserialize`) maps `object_bytes` in
+/// and resolves `header.trampoline_symbols` / `header.function_symbols`
+/// against it.
+pub fn load_object(path: &Path) -> io::Result<LoadedObject> {
+    let bytes = std::fs::read(path)?;
+    if bytes.len() < MAGIC.len() || &bytes[..MAGIC.len()] != MAGIC {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "not a wasmer AOT object (bad magic)",
+        ));
+    }
+
+    let mut offset = MAGIC.len();
+    if bytes.len() < offset + 8 {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "truncated AOT object: missing header length",
+        ));
+    }
+    let header_len = u64::from_le_bytes(bytes[offset..offset + 8].try_into().unwrap()) as usize;
+    offset += 8;
+
+    if bytes.len() < offset + header_len {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "truncated AOT object: header shorter than declared length",
+        ));
+    }
+    let header: AotMetadata = bincode::deserialize(&bytes[offset..offset + header_len])
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err.to_string()))?;
+    offset += header_len;
+
+    if header.version != HEADER_VERSION {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "unsupported AOT object version {} (expected {})",
+                header.version, HEADER_VERSION
+            ),
+        ));
+    }
+
+    Ok(LoadedObject {
+        header,
+        object_bytes: bytes[offset..].to_vec(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_header() -> AotMetadata {
+        AotMetadata::new(
+            vec!["trmp0".to_string()],
+            vec!["fn0".to_string()],
+            Some(1),
+            None,
+        )
+    }
+
+    fn encode(header: &AotMetadata, object_bytes: &[u8]) -> Vec<u8> {
+        let encoded_header = bincode::serialize(header).unwrap();
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(MAGIC);
+        bytes.extend_from_slice(&(encoded_header.len() as u64).to_le_bytes());
+        bytes.extend_from_slice(&encoded_header);
+        bytes.extend_from_slice(object_bytes);
+        bytes
+    }
+
+    #[test]
+    fn load_object_round_trips_a_well_formed_artifact() {
+        let dir = std::env::temp_dir();
+        let path = dir.join("wasmer_aot_test_round_trip.bin");
+        std::fs::write(&path, encode(&sample_header(), b"\x7fELF...")).unwrap();
+
+        let loaded = load_object(&path).unwrap();
+        assert_eq!(loaded.header.trampoline_symbols, vec!["trmp0".to_string()]);
+        assert_eq!(loaded.object_bytes, b"\x7fELF...".to_vec());
+
+        std::fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn load_object_rejects_bad_magic() {
+        let dir = std::env::temp_dir();
+        let path = dir.join("wasmer_aot_test_bad_magic.bin");
+        std::fs::write(&path, b"NOTWASM\0garbage").unwrap();
+
+        let err = load_object(&path).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        std::fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn load_object_rejects_truncated_header_length() {
+        let dir = std::env::temp_dir();
+        let path = dir.join("wasmer_aot_test_truncated_len.bin");
+        // Magic plus only a few bytes of the 8-byte header-length field.
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend_from_slice(&[0, 0, 0]);
+        std::fs::write(&path, &bytes).unwrap();
+
+        let err = load_object(&path).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+
+        std::fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn load_object_rejects_header_shorter_than_declared_length() {
+        let dir = std::env::temp_dir();
+        let path = dir.join("wasmer_aot_test_truncated_header.bin");
+        let encoded_header = bincode::serialize(&sample_header()).unwrap();
+        let mut bytes = MAGIC.to_vec();
+        // Claim a header twice as long as what actually follows.
+        bytes.extend_from_slice(&((encoded_header.len() * 2) as u64).to_le_bytes());
+        bytes.extend_from_slice(&encoded_header);
+        std::fs::write(&path, &bytes).unwrap();
+
+        let err = load_object(&path).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+
+        std::fs::remove_file(&path).ok();
+    }
+}
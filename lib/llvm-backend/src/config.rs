@@ -24,6 +24,28 @@ pub struct LLVMConfig {
     /// The optimization levels when optimizing the IR.
     pub opt_level: OptimizationLevel,
 
+    /// The relocation model used when generating code.
+    ///
+    /// Defaults to `RelocMode::Static`, matching prior behavior. Set this to
+    /// `RelocMode::PIC` to produce position-independent code suitable for
+    /// `dlopen`-style loading of AOT artifacts (see [`crate::aot`]); it is
+    /// only valid together with [`CodeModel::Small`] or [`CodeModel::Kernel`].
+    pub reloc_mode: RelocMode,
+
+    /// The code model used when generating code.
+    ///
+    /// Defaults to `CodeModel::Large`, matching prior behavior. Position-
+    /// independent code (`RelocMode::PIC`) requires `CodeModel::Small` (or
+    /// `CodeModel::Kernel`).
+    pub code_model: CodeModel,
+
+    /// Instrument generated code with execution counters (see
+    /// [`crate::coverage`]), so a host can dump a coverage report after
+    /// execution. Defaults to `false`; instrumentation adds overhead to
+    /// every compiled block, so it should only be turned on for coverage
+    /// runs.
+    pub enable_coverage: bool,
+
     features: Features,
     target: Target,
 }
@@ -36,16 +58,44 @@ impl LLVMConfig {
             enable_nan_canonicalization: true,
             enable_verifier: false,
             opt_level: OptimizationLevel::Aggressive,
+            reloc_mode: RelocMode::Static,
+            code_model: CodeModel::Large,
+            enable_coverage: false,
             features: Default::default(),
             target: Default::default(),
         }
     }
-    fn reloc_mode(&self) -> RelocMode {
-        RelocMode::Static
+
+    /// Validates that `reloc_mode` and `code_model` are a supported
+    /// combination, returning an error message otherwise. `RelocMode::PIC`
+    /// requires a code model small enough to be relocated at load time.
+    fn validate_reloc_and_code_model(&self) -> Result<(), String> {
+        if self.reloc_mode == RelocMode::PIC
+            && !matches!(self.code_model, CodeModel::Small | CodeModel::Kernel)
+        {
+            return Err(format!(
+                "RelocMode::PIC requires CodeModel::Small (or ::Kernel), got {:?}",
+                self.code_model
+            ));
+        }
+        Ok(())
     }
 
-    fn code_model(&self) -> CodeModel {
-        CodeModel::Large
+    /// Whether the configured target advertises a vector feature wide
+    /// enough to natively lower WebAssembly's `V128` type (SSE2 on x86_64,
+    /// NEON on AArch64).
+    ///
+    /// This only reports the target's capability; it isn't consulted by any
+    /// `V128` lowering yet; the SIMD opcode codegen that would need to
+    /// branch on it (scalar-pair fallback instead of a native vector
+    /// instruction) isn't part of this backend's source tree.
+    pub fn has_v128_support(&self) -> bool {
+        let cpu_features = self.target().cpu_features();
+        match self.target().triple().architecture {
+            Architecture::X86_64 => cpu_features.contains(CpuFeature::SSE2),
+            Architecture::Arm(_) => cpu_features.contains(CpuFeature::NEON),
+            _ => false,
+        }
     }
 
     /// Generates the target machine for the current target
@@ -54,6 +104,9 @@ impl LLVMConfig {
         let triple = target.triple();
         let cpu_features = target.cpu_features().clone();
 
+        self.validate_reloc_and_code_model()
+            .unwrap_or_else(|err| panic!("{}", err));
+
         match triple.architecture {
             Architecture::X86_64 => LLVMTarget::initialize_x86(&InitializationConfig {
                 asm_parser: true,
@@ -74,17 +127,30 @@ impl LLVMConfig {
             _ => unimplemented!("target {} not supported", triple),
         }
 
-        if !cpu_features.contains(CpuFeature::AVX2) {
-            panic!("The target needs to support AVX2");
-        }
-
-        // The cpu features formatted as LLVM strings
-        let llvm_cpu_features = cpu_features.iter().filter_map(|feature| {
-            match feature {
+        // The cpu features formatted as LLVM strings. Every feature the
+        // runtime can detect is translated, rather than requiring a single
+        // fixed baseline (previously AVX2) -- this keeps the LLVM backend
+        // usable on older x86_64 hosts and on ARM that don't advertise it.
+        let llvm_cpu_features = cpu_features
+            .iter()
+            .filter_map(|feature| match feature {
+                CpuFeature::SSE2 => Some("+sse2"),
+                CpuFeature::SSE3 => Some("+sse3"),
+                CpuFeature::SSSE3 => Some("+ssse3"),
+                CpuFeature::SSE41 => Some("+sse4.1"),
+                CpuFeature::SSE42 => Some("+sse4.2"),
+                CpuFeature::POPCNT => Some("+popcnt"),
+                CpuFeature::AVX => Some("+avx"),
                 CpuFeature::AVX2 => Some("+avx2"),
-                _ => None
-            }
-        }).join(" ");
+                CpuFeature::AVX512F => Some("+avx512f"),
+                CpuFeature::BMI1 => Some("+bmi1"),
+                CpuFeature::BMI2 => Some("+bmi2"),
+                CpuFeature::LZCNT => Some("+lzcnt"),
+                CpuFeature::FMA => Some("+fma"),
+                CpuFeature::NEON => Some("+neon"),
+                _ => None,
+            })
+            .join(" ");
 
         let arch_string = triple.architecture.to_string();
         let llvm_target = LLVMTarget::from_name(&arch_string).unwrap();
@@ -93,14 +159,51 @@ impl LLVMConfig {
             &arch_string,
             &llvm_cpu_features,
             self.opt_level.clone(),
-            self.reloc_mode(),
-            self.code_model(),
+            self.reloc_mode,
+            self.code_model,
         )
         .unwrap();
         target_machine
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pic_with_large_code_model_is_rejected() {
+        let mut config = LLVMConfig::new();
+        config.reloc_mode = RelocMode::PIC;
+        config.code_model = CodeModel::Large;
+        assert!(config.validate_reloc_and_code_model().is_err());
+    }
+
+    #[test]
+    fn pic_with_small_code_model_is_accepted() {
+        let mut config = LLVMConfig::new();
+        config.reloc_mode = RelocMode::PIC;
+        config.code_model = CodeModel::Small;
+        assert!(config.validate_reloc_and_code_model().is_ok());
+    }
+
+    #[test]
+    fn pic_with_kernel_code_model_is_accepted() {
+        let mut config = LLVMConfig::new();
+        config.reloc_mode = RelocMode::PIC;
+        config.code_model = CodeModel::Kernel;
+        assert!(config.validate_reloc_and_code_model().is_ok());
+    }
+
+    #[test]
+    fn static_with_large_code_model_is_accepted() {
+        let mut config = LLVMConfig::new();
+        config.reloc_mode = RelocMode::Static;
+        config.code_model = CodeModel::Large;
+        assert!(config.validate_reloc_and_code_model().is_ok());
+    }
+}
+
 impl CompilerConfig for LLVMConfig {
     /// Gets the WebAssembly features
     fn features(&self) -> &Features {
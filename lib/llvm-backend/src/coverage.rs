@@ -0,0 +1,167 @@
+//! Source-mapped coverage instrumentation, mirroring rustc's coverage
+//! subsystem: each wasm basic block is assigned a [`CounterId`] that's
+//! incremented on entry, and most blocks are reconstructed from derived
+//! [`Expression`] counters instead of being instrumented directly. Since
+//! wasm has no source lines, a [`Region`] is expressed in wasm bytecode
+//! offsets rather than rustc's line/column spans.
+
+use std::collections::HashMap;
+
+/// Identifies one physical, incremented counter within a function's slice
+/// of the module-wide counter array.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct CounterId(pub u32);
+
+/// The arithmetic operation combining the two operands of an [`Expression`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExprOp {
+    Add,
+    Sub,
+}
+
+/// A counter derived from two others (`lhs op rhs`) rather than
+/// incremented directly, so a block's count can be reconstructed without
+/// instrumenting every edge.
+#[derive(Debug, Clone, Copy)]
+pub struct Expression {
+    pub lhs: CounterId,
+    pub op: ExprOp,
+    pub rhs: CounterId,
+}
+
+/// A `[start_offset, end_offset)` span of wasm bytecode that a mapping
+/// entry covers. `start_offset`/`end_offset` play the role rustc's
+/// `SourceRegion` line/column span plays for real source files.
+#[derive(Debug, Clone, Copy)]
+pub struct Region {
+    pub start_offset: u32,
+    pub end_offset: u32,
+}
+
+/// What tracks the execution count of a [`Region`].
+#[derive(Debug, Clone, Copy)]
+pub enum CounterKind {
+    /// A physical, directly-incremented counter.
+    Code(CounterId),
+    /// A counter reconstructed from `expressions[index]`.
+    Expression(usize),
+    /// Never executed; contributes zero without needing a counter.
+    Zero,
+}
+
+/// One region-to-counter mapping table entry.
+#[derive(Debug, Clone, Copy)]
+pub struct MappingEntry {
+    pub region: Region,
+    pub kind: CounterKind,
+}
+
+/// The coverage data generated for a single function: how many physical
+/// counters it needs, the expressions derived from them, and the region
+/// mapping table a host uses to turn raw counts into a coverage report.
+#[derive(Debug, Clone, Default)]
+pub struct FunctionCoverage {
+    pub num_counters: u32,
+    pub expressions: Vec<Expression>,
+    pub mapping: Vec<MappingEntry>,
+}
+
+impl FunctionCoverage {
+    /// Assigns a fresh physical counter to `region` and records the
+    /// mapping entry for it, returning the `CounterId` the caller should
+    /// emit the entry-block increment for.
+    pub fn add_code_counter(&mut self, region: Region) -> CounterId {
+        let id = CounterId(self.num_counters);
+        self.num_counters += 1;
+        self.mapping.push(MappingEntry {
+            region,
+            kind: CounterKind::Code(id),
+        });
+        id
+    }
+
+    /// Records `region` as covered by a derived counter (`lhs op rhs`)
+    /// rather than a physical one, so it can be reconstructed without its
+    /// own increment.
+    pub fn add_expression_counter(&mut self, region: Region, lhs: CounterId, op: ExprOp, rhs: CounterId) {
+        let index = self.expressions.len();
+        self.expressions.push(Expression { lhs, op, rhs });
+        self.mapping.push(MappingEntry {
+            region,
+            kind: CounterKind::Expression(index),
+        });
+    }
+}
+
+/// The coverage map for an entire module: one [`FunctionCoverage`] per
+/// function, keyed by its trampoline/compiled symbol name, plus the total
+/// number of physical counter slots the VMContext-resident counter array
+/// must reserve.
+#[derive(Debug, Clone, Default)]
+pub struct CoverageMap {
+    pub functions: HashMap<String, FunctionCoverage>,
+    pub total_counters: u32,
+}
+
+impl CoverageMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers `coverage` under `name`, reserving its counters at the
+    /// end of the shared counter array and returning the base offset they
+    /// were assigned within it.
+    pub fn register_function(&mut self, name: String, coverage: FunctionCoverage) -> u32 {
+        let base = self.total_counters;
+        self.total_counters += coverage.num_counters;
+        self.functions.insert(name, coverage);
+        base
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn add_code_counter_assigns_increasing_ids() {
+        let mut fc = FunctionCoverage::default();
+        let a = fc.add_code_counter(Region { start_offset: 0, end_offset: 4 });
+        let b = fc.add_code_counter(Region { start_offset: 4, end_offset: 8 });
+        assert_eq!(a, CounterId(0));
+        assert_eq!(b, CounterId(1));
+        assert_eq!(fc.num_counters, 2);
+        assert_eq!(fc.mapping.len(), 2);
+    }
+
+    #[test]
+    fn add_expression_counter_does_not_consume_a_physical_counter() {
+        let mut fc = FunctionCoverage::default();
+        let a = fc.add_code_counter(Region { start_offset: 0, end_offset: 4 });
+        let b = fc.add_code_counter(Region { start_offset: 4, end_offset: 8 });
+        fc.add_expression_counter(Region { start_offset: 0, end_offset: 8 }, a, ExprOp::Add, b);
+        assert_eq!(fc.num_counters, 2);
+        assert_eq!(fc.expressions.len(), 1);
+        assert_eq!(fc.mapping.len(), 3);
+    }
+
+    #[test]
+    fn register_function_reserves_counters_at_successive_offsets() {
+        let mut map = CoverageMap::new();
+
+        let mut first = FunctionCoverage::default();
+        first.add_code_counter(Region { start_offset: 0, end_offset: 1 });
+        first.add_code_counter(Region { start_offset: 1, end_offset: 2 });
+        let first_base = map.register_function("trmp0".to_string(), first);
+
+        let mut second = FunctionCoverage::default();
+        second.add_code_counter(Region { start_offset: 0, end_offset: 1 });
+        let second_base = map.register_function("trmp1".to_string(), second);
+
+        assert_eq!(first_base, 0);
+        assert_eq!(second_base, 2);
+        assert_eq!(map.total_counters, 3);
+        assert!(map.functions.contains_key("trmp0"));
+        assert!(map.functions.contains_key("trmp1"));
+    }
+}
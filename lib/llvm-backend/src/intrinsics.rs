@@ -0,0 +1,82 @@
+//! LLVM intrinsic and common-type declarations shared across the backend's
+//! codegen modules, declared once against a module so every trampoline can
+//! reuse the same `FunctionValue`/`*Type` handles instead of re-declaring
+//! them per call site.
+
+use inkwell::context::Context;
+use inkwell::module::{Linkage, Module};
+use inkwell::types::{FloatType, IntType, PointerType, VoidType};
+use inkwell::values::FunctionValue;
+use inkwell::AddressSpace;
+
+pub struct Intrinsics<'ctx> {
+    pub void_ty: VoidType<'ctx>,
+    pub i32_ty: IntType<'ctx>,
+    pub i64_ty: IntType<'ctx>,
+    pub i128_ty: IntType<'ctx>,
+    pub f32_ty: FloatType<'ctx>,
+    pub f64_ty: FloatType<'ctx>,
+
+    pub i8_ptr_ty: PointerType<'ctx>,
+    pub i32_ptr_ty: PointerType<'ctx>,
+    pub i64_ptr_ty: PointerType<'ctx>,
+    pub i128_ptr_ty: PointerType<'ctx>,
+    pub f32_ptr_ty: PointerType<'ctx>,
+    pub f64_ptr_ty: PointerType<'ctx>,
+
+    /// Pointer to the opaque VMContext struct threaded through every
+    /// trampoline as its first argument.
+    pub ctx_ptr_ty: PointerType<'ctx>,
+
+    /// `declare void @llvm.va_start(i8*)` -- initializes a `va_list` from
+    /// the calling convention's variadic save area.
+    pub va_start: FunctionValue<'ctx>,
+    /// `declare void @llvm.va_end(i8*)` -- tears down a `va_list` built by
+    /// [`Self::va_start`]; required even on targets where it's a no-op.
+    pub va_end: FunctionValue<'ctx>,
+}
+
+impl<'ctx> Intrinsics<'ctx> {
+    /// Declares every type and intrinsic function this backend needs
+    /// against `module`, so later codegen only ever has to look them up on
+    /// `self` instead of redeclaring them.
+    pub fn declare(context: &'ctx Context, module: &Module<'ctx>) -> Self {
+        let void_ty = context.void_type();
+        let i32_ty = context.i32_type();
+        let i64_ty = context.i64_type();
+        let i128_ty = context.i128_type();
+        let f32_ty = context.f32_type();
+        let f64_ty = context.f64_type();
+
+        let i8_ptr_ty = context.i8_type().ptr_type(AddressSpace::Generic);
+        let i32_ptr_ty = i32_ty.ptr_type(AddressSpace::Generic);
+        let i64_ptr_ty = i64_ty.ptr_type(AddressSpace::Generic);
+        let i128_ptr_ty = i128_ty.ptr_type(AddressSpace::Generic);
+        let f32_ptr_ty = f32_ty.ptr_type(AddressSpace::Generic);
+        let f64_ptr_ty = f64_ty.ptr_type(AddressSpace::Generic);
+
+        let ctx_ptr_ty = i8_ptr_ty;
+
+        let va_list_fn_ty = void_ty.fn_type(&[i8_ptr_ty.into()], false);
+        let va_start = module.add_function("llvm.va_start", va_list_fn_ty, Some(Linkage::External));
+        let va_end = module.add_function("llvm.va_end", va_list_fn_ty, Some(Linkage::External));
+
+        Self {
+            void_ty,
+            i32_ty,
+            i64_ty,
+            i128_ty,
+            f32_ty,
+            f64_ty,
+            i8_ptr_ty,
+            i32_ptr_ty,
+            i64_ptr_ty,
+            i128_ptr_ty,
+            f32_ptr_ty,
+            f64_ptr_ty,
+            ctx_ptr_ty,
+            va_start,
+            va_end,
+        }
+    }
+}
@@ -1,29 +1,59 @@
-use crate::{abi, intrinsics::Intrinsics};
+use crate::{
+    abi,
+    coverage::{CoverageMap, FunctionCoverage, Region},
+    intrinsics::Intrinsics,
+};
 use inkwell::{
     attributes::{Attribute, AttributeLoc},
     builder::Builder,
     context::Context,
     module::{Linkage, Module},
+    targets::RelocMode,
     types::{BasicType, BasicTypeEnum, FunctionType},
-    values::FunctionValue,
-    AddressSpace,
+    values::{BasicValueEnum, FunctionValue, GlobalValue, PointerValue},
+    AddressSpace, GlobalVisibility,
 };
+use wasmer_compiler::Target;
 use wasmer_runtime_core::{
     module::ModuleInfo,
     structures::{SliceMap, TypedIndex},
     types::{FuncSig, SigIndex, Type},
 };
 
+/// For a variadic signature, the number of leading parameters that are part
+/// of the function's fixed arity. Any parameters past this point are the
+/// runtime-supplied variadic tail, forwarded through a `va_list` with the
+/// SysV x86_64 layout on every target (AArch64's AAPCS64 `va_list` layout is
+/// not built). A signature absent from this map is treated as non-variadic.
+pub type VariadicSigs = SliceMap<SigIndex, Option<usize>>;
+
 pub fn generate_trampolines<'ctx>(
     info: &ModuleInfo,
     signatures: &SliceMap<SigIndex, (FunctionType<'ctx>, Vec<(Attribute, AttributeLoc)>)>,
+    variadic_sigs: &VariadicSigs,
+    target: &Target,
+    reloc_mode: RelocMode,
+    mut coverage: Option<&mut CoverageMap>,
     module: &Module<'ctx>,
     context: &'ctx Context,
     builder: &Builder<'ctx>,
     intrinsics: &Intrinsics<'ctx>,
 ) -> Result<(), String> {
+    // One trampoline is generated per signature below, and each gets
+    // exactly one entry counter, reserved after the counters `coverage`
+    // already holds, so the shared counter array needs that many `i64`
+    // slots on top of the existing total.
+    let counters_global = coverage.as_deref().map(|coverage| {
+        allocate_coverage_counters(
+            coverage.total_counters + info.signatures.len() as u32,
+            module,
+            intrinsics,
+        )
+    });
+
     for (sig_index, sig) in info.signatures.iter() {
         let (func_type, func_attrs) = &signatures[sig_index];
+        let fixed_arity = variadic_sigs.get(sig_index).copied().flatten();
 
         let trampoline_sig = intrinsics.void_ty.fn_type(
             &[
@@ -37,16 +67,41 @@ pub fn generate_trampolines<'ctx>(
             false,
         );
 
-        let trampoline_func = module.add_function(
-            &format!("trmp{}", sig_index.index()),
-            trampoline_sig,
-            Some(Linkage::External),
-        );
+        let symbol = format!("trmp{}", sig_index.index());
+        let trampoline_func = module.add_function(&symbol, trampoline_sig, Some(Linkage::External));
+
+        if reloc_mode == RelocMode::PIC {
+            // Position-independent objects resolve intra-module calls
+            // locally; marking the trampoline hidden lets LLVM emit a
+            // GOT-free call and keeps the symbol out of the dynamic symbol
+            // table of the loaded shared object.
+            trampoline_func
+                .as_global_value()
+                .set_visibility(GlobalVisibility::Hidden);
+        }
+
+        // The trampoline's own body is the unit of coverage available at
+        // this codegen stage (per-wasm-basic-block counters are assigned
+        // further upstream, where the wasm bytecode offsets are known);
+        // it gets one `Code` counter for the whole function, mapped to the
+        // empty region `[0, 0)` since a trampoline has no wasm bytecode.
+        let coverage_slot = coverage.as_deref_mut().map(|coverage| {
+            let mut fc = FunctionCoverage::default();
+            let counter_id = fc.add_code_counter(Region {
+                start_offset: 0,
+                end_offset: 0,
+            });
+            let base = coverage.register_function(symbol.clone(), fc);
+            (counters_global.unwrap(), base + counter_id.0)
+        });
 
         generate_trampoline(
             trampoline_func,
             sig,
+            fixed_arity,
             &func_attrs,
+            target,
+            coverage_slot,
             context,
             builder,
             intrinsics,
@@ -68,7 +123,10 @@ pub fn type_to_llvm<'ctx>(intrinsics: &Intrinsics<'ctx>, ty: Type) -> BasicTypeE
 fn generate_trampoline<'ctx>(
     trampoline_func: FunctionValue,
     func_sig: &FuncSig,
+    fixed_arity: Option<usize>,
     func_attrs: &Vec<(Attribute, AttributeLoc)>,
+    target: &Target,
+    coverage_slot: Option<(GlobalValue<'ctx>, u32)>,
     context: &'ctx Context,
     builder: &Builder<'ctx>,
     intrinsics: &Intrinsics<'ctx>,
@@ -76,6 +134,10 @@ fn generate_trampoline<'ctx>(
     let entry_block = context.append_basic_block(trampoline_func, "entry");
     builder.position_at_end(entry_block);
 
+    if let Some((counters_global, slot)) = coverage_slot {
+        emit_counter_increment(builder, intrinsics, counters_global, slot);
+    }
+
     let (vmctx_ptr, func_ptr, args_ptr, returns_ptr) = match trampoline_func.get_params().as_slice()
     {
         &[vmctx_ptr, func_ptr, args_ptr, returns_ptr] => (
@@ -87,6 +149,22 @@ fn generate_trampoline<'ctx>(
         _ => return Err("trampoline function unimplemented".to_string()),
     };
 
+    if let Some(fixed_arity) = fixed_arity {
+        return generate_variadic_trampoline(
+            trampoline_func,
+            func_sig,
+            fixed_arity,
+            func_attrs,
+            context,
+            builder,
+            intrinsics,
+            vmctx_ptr,
+            func_ptr,
+            args_ptr,
+            returns_ptr,
+        );
+    }
+
     let cast_ptr_ty = |wasmer_ty| match wasmer_ty {
         Type::I32 => intrinsics.i32_ptr_ty,
         Type::F32 => intrinsics.f32_ptr_ty,
@@ -97,39 +175,14 @@ fn generate_trampoline<'ctx>(
 
     let mut args_vec = Vec::with_capacity(func_sig.params().len() + 1);
 
-    let func_sig_returns_bitwidths = func_sig
-        .returns()
-        .iter()
-        .map(|ty| match ty {
-            Type::I32 | Type::F32 => 32,
-            Type::I64 | Type::F64 => 64,
-            Type::V128 => 128,
-        })
-        .collect::<Vec<i32>>();
-
-    let _is_sret = match func_sig_returns_bitwidths.as_slice() {
-        []
-        | [_]
-        | [32, 64]
-        | [64, 32]
-        | [64, 64]
-        | [32, 32]
-        | [32, 32, 32]
-        | [32, 32, 64]
-        | [64, 32, 32]
-        | [32, 32, 32, 32] => false,
-        _ => {
-            let basic_types: Vec<_> = func_sig
-                .returns()
-                .iter()
-                .map(|&ty| type_to_llvm(intrinsics, ty))
-                .collect();
-
-            let sret_ty = context.struct_type(&basic_types, false);
-            args_vec.push(builder.build_alloca(sret_ty, "sret").into());
-
-            true
+    let return_abi = abi::classify_return(context, intrinsics, target, func_sig.returns());
+    let sret_ptr = match &return_abi {
+        abi::ReturnAbi::Indirect(sret_ty) => {
+            let sret_ptr = builder.build_alloca(*sret_ty, "sret");
+            args_vec.push(sret_ptr.into());
+            Some(sret_ptr)
         }
+        _ => None,
     };
 
     args_vec.push(vmctx_ptr);
@@ -154,11 +207,80 @@ fn generate_trampoline<'ctx>(
 
     let call_site = builder.build_call(func_ptr, &args_vec, "call");
 
+    if sret_ptr.is_some() {
+        let sret_kind_id = Attribute::get_named_enum_kind_id("sret");
+        let sret_attr = context.create_enum_attribute(sret_kind_id, 0);
+        call_site.add_attribute(AttributeLoc::Param(0), sret_attr);
+    }
     for (attr, attr_loc) in func_attrs {
         call_site.add_attribute(*attr_loc, *attr);
     }
 
-    let rets = abi::rets_from_call(builder, intrinsics, call_site, func_sig);
+    let rets = match &return_abi {
+        abi::ReturnAbi::Indirect(_) => {
+            abi::rets_from_sret(builder, intrinsics, sret_ptr.unwrap(), func_sig)
+        }
+        abi::ReturnAbi::Coerced(_) => abi::rets_from_coerced(builder, intrinsics, call_site, func_sig),
+        abi::ReturnAbi::Direct | abi::ReturnAbi::None => {
+            abi::rets_from_call(builder, intrinsics, call_site, func_sig)
+        }
+    };
+    store_rets(builder, intrinsics, returns_ptr, &rets);
+
+    builder.build_return(None);
+    Ok(())
+}
+
+/// Allocates the VMContext-resident counter array coverage instrumentation
+/// increments into, zero-initialized so a host reading it before the first
+/// call sees all-zero counts. One `i64` slot is reserved per physical
+/// counter across every instrumented function, at the offsets
+/// `CoverageMap::register_function` hands out.
+fn allocate_coverage_counters<'ctx>(
+    count: u32,
+    module: &Module<'ctx>,
+    intrinsics: &Intrinsics<'ctx>,
+) -> GlobalValue<'ctx> {
+    let counters_ty = intrinsics.i64_ty.array_type(count);
+    let counters_global = module.add_global(counters_ty, None, "wasmer_coverage_counters");
+    counters_global.set_initializer(&counters_ty.const_zero());
+    counters_global.set_linkage(Linkage::External);
+    counters_global
+}
+
+/// Emits `counters_global[slot] += 1` at the current insertion point, the
+/// entry-counter increment every instrumented trampoline gets at the top of
+/// its body.
+fn emit_counter_increment<'ctx>(
+    builder: &Builder<'ctx>,
+    intrinsics: &Intrinsics<'ctx>,
+    counters_global: GlobalValue<'ctx>,
+    slot: u32,
+) {
+    let counter_ptr = unsafe {
+        builder.build_in_bounds_gep(
+            counters_global.as_pointer_value(),
+            &[
+                intrinsics.i32_ty.const_zero(),
+                intrinsics.i32_ty.const_int(slot as u64, false),
+            ],
+            "coverage_counter_slot",
+        )
+    };
+    let count = builder.build_load(counter_ptr, "coverage_count").into_int_value();
+    let incremented = builder.build_int_add(count, intrinsics.i64_ty.const_int(1, false), "coverage_count_incr");
+    builder.build_store(counter_ptr, incremented);
+}
+
+/// Writes each returned value back through `returns_ptr`, matching the
+/// layout `generate_trampoline`'s caller expects (128-bit values occupy two
+/// 64-bit slots).
+fn store_rets<'ctx>(
+    builder: &Builder<'ctx>,
+    intrinsics: &Intrinsics<'ctx>,
+    returns_ptr: PointerValue<'ctx>,
+    rets: &[BasicValueEnum<'ctx>],
+) {
     let mut idx = 0;
     rets.iter().for_each(|v| {
         let ptr = unsafe {
@@ -171,7 +293,255 @@ fn generate_trampoline<'ctx>(
         }
         idx = idx + 1;
     });
+}
+
+/// Generates a trampoline for a variadic host/native function, e.g. the
+/// emscripten `printf` family.
+///
+/// The fixed leading parameters (`func_sig.params()[..fixed_arity]`) are
+/// passed as ordinary arguments, exactly like `generate_trampoline` does for
+/// a non-variadic callee. The remaining, runtime-supplied trailing arguments
+/// are classified and packed into a SysV x86_64-shaped `va_list` (the
+/// four-field `{ gp_offset, fp_offset, overflow_arg_area, reg_save_area }`
+/// struct `va_start`/`va_end` operate on), built with that same layout
+/// whatever the target, and forwarded to the callee as a
+/// single `va_list*`, mirroring how libcore's own variadic FFI shims build
+/// their register-save area.
+fn generate_variadic_trampoline<'ctx>(
+    trampoline_func: FunctionValue<'ctx>,
+    func_sig: &FuncSig,
+    fixed_arity: usize,
+    func_attrs: &Vec<(Attribute, AttributeLoc)>,
+    context: &'ctx Context,
+    builder: &Builder<'ctx>,
+    intrinsics: &Intrinsics<'ctx>,
+    vmctx_ptr: BasicValueEnum<'ctx>,
+    func_ptr: PointerValue<'ctx>,
+    args_ptr: PointerValue<'ctx>,
+    returns_ptr: PointerValue<'ctx>,
+) -> Result<(), String> {
+    let cast_ptr_ty = |wasmer_ty| match wasmer_ty {
+        Type::I32 => intrinsics.i32_ptr_ty,
+        Type::F32 => intrinsics.f32_ptr_ty,
+        Type::I64 => intrinsics.i64_ptr_ty,
+        Type::F64 => intrinsics.f64_ptr_ty,
+        Type::V128 => intrinsics.i128_ptr_ty,
+    };
 
+    let load_arg = |i: &mut u64, param_ty: Type| -> BasicValueEnum<'ctx> {
+        let index = intrinsics.i32_ty.const_int(*i, false);
+        let item_pointer = unsafe { builder.build_in_bounds_gep(args_ptr, &[index], "arg_ptr") };
+        let typed_item_pointer =
+            builder.build_pointer_cast(item_pointer, cast_ptr_ty(param_ty), "typed_arg_pointer");
+        let arg = builder.build_load(typed_item_pointer, "arg");
+        *i += 1;
+        if param_ty == Type::V128 {
+            *i += 1;
+        }
+        arg
+    };
+
+    // Fixed, statically-typed leading arguments.
+    let mut args_vec = Vec::with_capacity(fixed_arity + 2);
+    args_vec.push(vmctx_ptr);
+    let mut i: u64 = 0;
+    for param_ty in func_sig.params().iter().take(fixed_arity) {
+        args_vec.push(load_arg(&mut i, *param_ty));
+    }
+
+    // Build the va_list and populate it from the remaining trailing
+    // arguments, classified as INTEGER or SSE the way SysV x86_64 does.
+    let va_list_ptr = build_va_list(context, builder, intrinsics);
+    let reg_save_area = va_list::reg_save_area(builder, va_list_ptr);
+    let overflow_arg_area = va_list::overflow_arg_area(builder, va_list_ptr);
+
+    let mut gp_offset: u64 = 0;
+    let mut fp_offset: u64 = va_list::GP_REGS_SIZE;
+    let mut overflow_offset: u64 = 0;
+    for param_ty in func_sig.params().iter().skip(fixed_arity) {
+        if *param_ty == Type::V128 {
+            // `V128` doesn't fit this register-save area's 8-byte GP/SSE
+            // slot granularity (it's 16 bytes, twice an SSE slot), and SysV
+            // doesn't pass vector arguments through `va_arg` the way it
+            // does INTEGER/SSE ones -- rather than silently spilling it
+            // across two slots and corrupting whatever's saved next to it,
+            // refuse the variadic call outright.
+            return Err("variadic trailing arguments of type V128 are not supported".to_string());
+        }
+        let value = load_arg(&mut i, *param_ty);
+        let is_sse = matches!(param_ty, Type::F32 | Type::F64);
+        let (area, offset, slot_size, limit) = if is_sse {
+            // `fp_offset` starts at `GP_REGS_SIZE` (the SSE save area sits
+            // right after the GP save area), so its cutoff is that same
+            // absolute offset plus the SSE area's own size, not the size
+            // alone -- matching the clamp `set_fp_offset` below applies.
+            (
+                reg_save_area,
+                &mut fp_offset,
+                va_list::SSE_SLOT_SIZE,
+                va_list::GP_REGS_SIZE + va_list::SSE_REGS_LIMIT,
+            )
+        } else {
+            (reg_save_area, &mut gp_offset, va_list::GP_SLOT_SIZE, va_list::GP_REGS_LIMIT)
+        };
+
+        if *offset < limit {
+            let slot = unsafe {
+                builder.build_in_bounds_gep(
+                    area,
+                    &[intrinsics.i32_ty.const_int(*offset, false)],
+                    "reg_save_slot",
+                )
+            };
+            let typed_slot =
+                builder.build_pointer_cast(slot, value.get_type().ptr_type(AddressSpace::Generic), "");
+            builder.build_store(typed_slot, value);
+            *offset += slot_size;
+        } else {
+            let slot = unsafe {
+                builder.build_in_bounds_gep(
+                    overflow_arg_area,
+                    &[intrinsics.i32_ty.const_int(overflow_offset, false)],
+                    "overflow_slot",
+                )
+            };
+            let typed_slot =
+                builder.build_pointer_cast(slot, value.get_type().ptr_type(AddressSpace::Generic), "");
+            builder.build_store(typed_slot, value);
+            overflow_offset += 8;
+        }
+    }
+    va_list::set_gp_offset(builder, intrinsics, va_list_ptr, gp_offset.min(va_list::GP_REGS_LIMIT));
+    va_list::set_fp_offset(builder, intrinsics, va_list_ptr, fp_offset.min(va_list::GP_REGS_SIZE + va_list::SSE_REGS_LIMIT));
+
+    args_vec.push(va_list_ptr.into());
+
+    let call_site = builder.build_call(func_ptr, &args_vec, "call");
+    for (attr, attr_loc) in func_attrs {
+        call_site.add_attribute(*attr_loc, *attr);
+    }
+
+    let rets = abi::rets_from_call(builder, intrinsics, call_site, func_sig);
+    store_rets(builder, intrinsics, returns_ptr, &rets);
+
+    builder.build_call(intrinsics.va_end, &[va_list_ptr.into()], "");
     builder.build_return(None);
     Ok(())
 }
+
+/// Allocates and `llvm.va_start`s a SysV x86_64 `va_list`:
+/// `{ i32 gp_offset, i32 fp_offset, i8* overflow_arg_area, i8* reg_save_area }`.
+fn build_va_list<'ctx>(
+    context: &'ctx Context,
+    builder: &Builder<'ctx>,
+    intrinsics: &Intrinsics<'ctx>,
+) -> PointerValue<'ctx> {
+    let va_list_ty = context.struct_type(
+        &[
+            intrinsics.i32_ty.as_basic_type_enum(),
+            intrinsics.i32_ty.as_basic_type_enum(),
+            intrinsics.i8_ptr_ty.as_basic_type_enum(),
+            intrinsics.i8_ptr_ty.as_basic_type_enum(),
+        ],
+        false,
+    );
+    let reg_save_area_ty = context.i8_type().array_type(
+        (va_list::GP_REGS_SIZE + va_list::SSE_REGS_LIMIT) as u32,
+    );
+    let overflow_area_ty = context.i8_type().array_type(va_list::OVERFLOW_AREA_SIZE as u32);
+
+    let va_list_ptr = builder.build_alloca(va_list_ty, "va_list");
+    let reg_save_area = builder.build_alloca(reg_save_area_ty, "reg_save_area");
+    let overflow_area = builder.build_alloca(overflow_area_ty, "overflow_arg_area");
+
+    let reg_save_area = builder.build_pointer_cast(reg_save_area, intrinsics.i8_ptr_ty, "");
+    let overflow_area = builder.build_pointer_cast(overflow_area, intrinsics.i8_ptr_ty, "");
+
+    va_list::set_gp_offset(builder, intrinsics, va_list_ptr, 0);
+    va_list::set_fp_offset(builder, intrinsics, va_list_ptr, va_list::GP_REGS_SIZE);
+    va_list::set_overflow_arg_area(builder, va_list_ptr, overflow_area);
+    va_list::set_reg_save_area(builder, va_list_ptr, reg_save_area);
+
+    let va_list_i8_ptr = builder.build_pointer_cast(va_list_ptr, intrinsics.i8_ptr_ty, "");
+    builder.build_call(intrinsics.va_start, &[va_list_i8_ptr.into()], "");
+
+    va_list_ptr
+}
+
+/// Field layout and register-class limits for the SysV x86_64 `va_list`,
+/// shared between `build_va_list` and the argument-packing loop.
+mod va_list {
+    use super::*;
+
+    pub const GP_SLOT_SIZE: u64 = 8;
+    pub const SSE_SLOT_SIZE: u64 = 16;
+    /// Six 8-byte integer registers (`rdi, rsi, rdx, rcx, r8, r9`).
+    pub const GP_REGS_SIZE: u64 = 6 * GP_SLOT_SIZE;
+    pub const GP_REGS_LIMIT: u64 = GP_REGS_SIZE;
+    /// Eight 16-byte SSE registers (`xmm0..xmm7`).
+    pub const SSE_REGS_LIMIT: u64 = 8 * SSE_SLOT_SIZE;
+    pub const OVERFLOW_AREA_SIZE: u64 = 256;
+
+    fn gep<'ctx>(builder: &Builder<'ctx>, va_list_ptr: PointerValue<'ctx>, field: u64) -> PointerValue<'ctx> {
+        builder
+            .build_struct_gep(va_list_ptr, field as u32, "va_list_field")
+            .unwrap()
+    }
+
+    pub fn set_gp_offset<'ctx>(
+        builder: &Builder<'ctx>,
+        intrinsics: &Intrinsics<'ctx>,
+        va_list_ptr: PointerValue<'ctx>,
+        value: u64,
+    ) {
+        builder.build_store(gep(builder, va_list_ptr, 0), intrinsics.i32_ty.const_int(value, false));
+    }
+
+    pub fn set_fp_offset<'ctx>(
+        builder: &Builder<'ctx>,
+        intrinsics: &Intrinsics<'ctx>,
+        va_list_ptr: PointerValue<'ctx>,
+        value: u64,
+    ) {
+        builder.build_store(gep(builder, va_list_ptr, 1), intrinsics.i32_ty.const_int(value, false));
+    }
+
+    pub fn set_overflow_arg_area<'ctx>(
+        builder: &Builder<'ctx>,
+        va_list_ptr: PointerValue<'ctx>,
+        value: PointerValue<'ctx>,
+    ) {
+        builder.build_store(gep(builder, va_list_ptr, 2), value);
+    }
+
+    pub fn set_reg_save_area<'ctx>(
+        builder: &Builder<'ctx>,
+        va_list_ptr: PointerValue<'ctx>,
+        value: PointerValue<'ctx>,
+    ) {
+        builder.build_store(gep(builder, va_list_ptr, 3), value);
+    }
+
+    pub fn reg_save_area<'ctx>(builder: &Builder<'ctx>, va_list_ptr: PointerValue<'ctx>) -> PointerValue<'ctx> {
+        builder.build_load(gep(builder, va_list_ptr, 3), "reg_save_area").into_pointer_value()
+    }
+
+    pub fn overflow_arg_area<'ctx>(builder: &Builder<'ctx>, va_list_ptr: PointerValue<'ctx>) -> PointerValue<'ctx> {
+        builder.build_load(gep(builder, va_list_ptr, 2), "overflow_arg_area").into_pointer_value()
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        // The packing loop's SSE cutoff and the clamp `set_fp_offset` is
+        // given are both `GP_REGS_SIZE + SSE_REGS_LIMIT`; this pins that sum
+        // to SysV x86_64's 176-byte register save area (6 * 8 + 8 * 16).
+        #[test]
+        fn save_area_cutoff_matches_fp_offset_clamp() {
+            assert_eq!(GP_REGS_SIZE, 48);
+            assert_eq!(SSE_REGS_LIMIT, 128);
+            assert_eq!(GP_REGS_SIZE + SSE_REGS_LIMIT, 176);
+        }
+    }
+}